@@ -22,14 +22,23 @@
 
 //! Functions for conversion between binary and hex string.
 
-use std::{
-    fmt::{LowerHex, Write},
-    num::ParseIntError,
-};
+use std::fmt;
 
-use serde::Serializer;
+use serde::{de::Error as SerdeDeError, Deserializer, Serializer};
 use thiserror::Error;
 
+const HEX_CHARS_LOWER: &[u8; 16] = b"0123456789abcdef";
+
+/// Map a single ASCII hex digit to its nibble value, or `None` if it is not a valid hex character.
+fn hex_nibble(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
 /// Any object implementing this trait has the ability to represent itself as a hexadecimal string and convert from it.
 pub trait Hex {
     /// Try to convert the given hexadecimal string to the type.
@@ -42,58 +51,243 @@ pub trait Hex {
 
     /// Return the hexadecimal string representation of the type.
     fn to_hex(&self) -> String;
+
+    /// Return the upper-case hexadecimal string representation of the type.
+    fn to_hex_upper(&self) -> String {
+        self.to_hex().to_uppercase()
+    }
 }
 
 /// Errors for [Hex] trait.
 #[derive(Debug, Error)]
 #[allow(missing_docs)]
 pub enum HexError {
-    #[error("Only hexadecimal characters (0-9,a-f) are permitted")]
-    InvalidCharacter(#[from] ParseIntError),
+    #[error("Invalid character '{c}' at index {index}: only hexadecimal characters (0-9,a-f,A-F) are permitted")]
+    InvalidCharacter { c: char, index: usize },
     #[error("Hex string lengths must be a multiple of 2")]
     LengthError,
     #[error("Invalid hex representation for the target type")]
     HexConversionError,
+    #[error("Output buffer has the wrong length for this operation")]
+    InvalidBufferLength,
 }
 
-/// Encode the provided bytes into a hex string.
-pub fn to_hex<T>(bytes: &[T]) -> String
-where T: LowerHex {
-    let mut s = String::with_capacity(bytes.len() * 2);
-    for byte in bytes {
-        write!(&mut s, "{:02x}", byte).expect("Unable to write");
+/// A trait for types that can write their hexadecimal representation directly into a formatter, without allocating
+/// an intermediate `String`. It is implemented for every type that can be viewed as a byte slice.
+pub trait ToHex {
+    /// Write the lower-case hexadecimal representation of `self` into `w`.
+    fn write_hex<W: fmt::Write>(&self, w: &mut W) -> fmt::Result;
+
+    /// Write the upper-case hexadecimal representation of `self` into `w`.
+    fn write_hex_upper<W: fmt::Write>(&self, w: &mut W) -> fmt::Result;
+}
+
+impl<T: AsRef<[u8]>> ToHex for T {
+    fn write_hex<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        for byte in self.as_ref() {
+            write!(w, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+
+    fn write_hex_upper<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        for byte in self.as_ref() {
+            write!(w, "{:02X}", byte)?;
+        }
+        Ok(())
     }
-    s
 }
 
-/// Encode the provided vector of bytes into a hex string.
-pub fn to_hex_multiple(bytearray: &[Vec<u8>]) -> Vec<String> {
-    let mut result = Vec::new();
-    for bytes in bytearray {
-        result.push(to_hex(bytes))
+/// A trait for types that can be decoded from a hexadecimal string. Its method is named distinctly from
+/// [Hex::from_hex] so that a type implementing both traits isn't forced into UFCS to disambiguate which `from_hex`
+/// it means. Mirrors [ToHex] and is implemented for `Vec<u8>`.
+pub trait FromHex: Sized {
+    /// Try to decode the given hexadecimal string into `Self`.
+    fn decode(hex: &str) -> Result<Self, HexError>;
+}
+
+impl FromHex for Vec<u8> {
+    fn decode(hex: &str) -> Result<Self, HexError> {
+        from_hex(hex)
     }
-    result
 }
 
-/// Decode a hex string into bytes.
-pub fn from_hex(hex_str: &str) -> Result<Vec<u8>, HexError> {
-    let hex_trim = hex_str.trim();
+/// Encode `data` as a hex string directly into the caller-supplied buffer `out`.
+///
+/// # Errors
+/// Returns [HexError::InvalidBufferLength] if `out.len() != 2 * data.len()`.
+pub fn encode_to_slice<T: AsRef<[u8]>>(data: T, out: &mut [u8]) -> Result<(), HexError> {
+    let data = data.as_ref();
+    if out.len() != data.len() * 2 {
+        return Err(HexError::InvalidBufferLength);
+    }
+    for (i, byte) in data.iter().enumerate() {
+        out[2 * i] = HEX_CHARS_LOWER[(byte >> 4) as usize];
+        out[2 * i + 1] = HEX_CHARS_LOWER[(byte & 0x0f) as usize];
+    }
+    Ok(())
+}
+
+/// Trim whitespace and an optional `0x` prefix from `hex`, checking that what remains is ASCII and a whole number
+/// of bytes. Shared by every decode entry point so the normalization rules can't drift between them.
+///
+/// Returns the normalized string together with the byte offset of its first character within the original `hex`,
+/// so that callers can translate a nibble position in the normalized string back to a position in `hex` as the
+/// caller actually wrote it (e.g. reporting [HexError::InvalidCharacter] against a `0x`-prefixed or
+/// whitespace-padded string).
+fn normalize_hex(hex: &str) -> Result<(&str, usize), HexError> {
+    let hex_trim = hex.trim();
     if hex_trim.len() % 2 == 1 {
         return Err(HexError::LengthError);
     }
-    if !hex_str.is_ascii() {
+    if !hex_trim.is_ascii() {
         return Err(HexError::HexConversionError);
     }
+    let mut offset = hex.len() - hex.trim_start().len();
     let hex_trim = if (hex_trim.len() >= 2) && (&hex_trim[..2] == "0x") {
+        offset += 2;
         &hex_trim[2..]
     } else {
         hex_trim
     };
-    let num_bytes = hex_trim.len() / 2;
-    let mut result = vec![0u8; num_bytes];
-    for i in 0..num_bytes {
-        result[i] = u8::from_str_radix(&hex_trim[2 * i..2 * (i + 1)], 16).map_err(HexError::InvalidCharacter)?;
+    Ok((hex_trim, offset))
+}
+
+/// Decode the hex string `hex` directly into the caller-supplied buffer `out`.
+///
+/// # Errors
+/// Returns [HexError::LengthError] if `hex` is not a whole number of bytes, or [HexError::InvalidBufferLength] if
+/// `out` is not exactly half the length of (the trimmed) `hex`.
+pub fn decode_to_slice(hex: &str, out: &mut [u8]) -> Result<(), HexError> {
+    let (hex_trim, offset) = normalize_hex(hex)?;
+    if out.len() != hex_trim.len() / 2 {
+        return Err(HexError::InvalidBufferLength);
+    }
+    let chars = hex_trim.as_bytes();
+    for (i, byte) in out.iter_mut().enumerate() {
+        let hi_index = 2 * i;
+        let hi = hex_nibble(chars[hi_index]).ok_or(HexError::InvalidCharacter {
+            c: chars[hi_index] as char,
+            index: hi_index + offset,
+        })?;
+        let lo_index = hi_index + 1;
+        let lo = hex_nibble(chars[lo_index]).ok_or(HexError::InvalidCharacter {
+            c: chars[lo_index] as char,
+            index: lo_index + offset,
+        })?;
+        *byte = (hi << 4) | lo;
     }
+    Ok(())
+}
+
+/// Map a single ASCII hex character to its nibble value using branchless, constant-time arithmetic instead of a
+/// match or early return, so that the time taken does not depend on whether (or which) character is invalid.
+///
+/// Returns `(value, is_valid)`; `value` is `0` when `is_valid` is `false`.
+fn hex_nibble_ct(c: u8) -> (u8, bool) {
+    let is_digit = ((c >= b'0') as u8) & ((c <= b'9') as u8);
+    let is_upper = ((c >= b'A') as u8) & ((c <= b'F') as u8);
+    let is_lower = ((c >= b'a') as u8) & ((c <= b'f') as u8);
+
+    let digit_val = c.wrapping_sub(b'0');
+    let upper_val = c.wrapping_sub(b'A').wrapping_add(10);
+    let lower_val = c.wrapping_sub(b'a').wrapping_add(10);
+
+    let value = (digit_val & is_digit.wrapping_neg())
+        | (upper_val & is_upper.wrapping_neg())
+        | (lower_val & is_lower.wrapping_neg());
+    (value, (is_digit | is_upper | is_lower) != 0)
+}
+
+/// Constant-time counterpart to [decode_to_slice], intended for decoding secret key material such as private keys
+/// and seeds. Every nibble is decoded using [hex_nibble_ct]'s branchless arithmetic and, once `hex` has passed the
+/// length, ASCII and buffer-size checks, `out` is always written in full, even when `hex` contains non-hex
+/// characters, so that the buffer a caller subsequently zeroizes is in a deterministic state regardless of where
+/// (or whether) a non-hex character occurs. Input that fails those checks (including input containing non-ASCII
+/// characters) returns an error before `out` is written. No early return is taken once the character loop
+/// starts; if one or more characters were invalid, a single aggregated
+/// [HexError::InvalidCharacter] referencing the last offending character is returned after the whole buffer has
+/// been processed.
+///
+/// # Errors
+/// Returns [HexError::LengthError] if `hex` is not a whole number of bytes, or [HexError::InvalidBufferLength] if
+/// `out` is not exactly half the length of (the trimmed) `hex`.
+pub fn decode_to_slice_ct(hex: &str, out: &mut [u8]) -> Result<(), HexError> {
+    let (hex_trim, offset) = normalize_hex(hex)?;
+    if out.len() != hex_trim.len() / 2 {
+        return Err(HexError::InvalidBufferLength);
+    }
+    let chars = hex_trim.as_bytes();
+    let mut error = None;
+    for (i, byte) in out.iter_mut().enumerate() {
+        let hi_index = 2 * i;
+        let lo_index = hi_index + 1;
+        let (hi, hi_valid) = hex_nibble_ct(chars[hi_index]);
+        let (lo, lo_valid) = hex_nibble_ct(chars[lo_index]);
+        if !hi_valid {
+            error = Some(HexError::InvalidCharacter {
+                c: chars[hi_index] as char,
+                index: hi_index + offset,
+            });
+        }
+        if !lo_valid {
+            error = Some(HexError::InvalidCharacter {
+                c: chars[lo_index] as char,
+                index: lo_index + offset,
+            });
+        }
+        *byte = (hi << 4) | lo;
+    }
+    error.map_or(Ok(()), Err)
+}
+
+/// Constant-time counterpart to [from_hex]. See [decode_to_slice_ct] for the timing guarantees this provides when
+/// decoding secret key material.
+pub fn from_hex_ct(hex_str: &str) -> Result<Vec<u8>, HexError> {
+    let (hex_trim, _) = normalize_hex(hex_str)?;
+    let mut result = vec![0u8; hex_trim.len() / 2];
+    decode_to_slice_ct(hex_str, &mut result)?;
+    Ok(result)
+}
+
+/// Constant-time counterpart to [to_hex], provided for symmetry with [from_hex_ct] when encoding secret key
+/// material. [encode_to_slice] has no data-dependent branches or early returns, so this simply allocates the
+/// output buffer for it; note that, unlike [decode_to_slice_ct], it does not guard against cache-timing leaks from
+/// the `HEX_CHARS_LOWER` table lookup.
+pub fn to_hex_ct(bytes: &[u8]) -> String {
+    let mut out = vec![0u8; bytes.len() * 2];
+    encode_to_slice(bytes, &mut out).expect("out is sized to exactly 2 * bytes.len()");
+    String::from_utf8(out).expect("hex output is always valid ASCII")
+}
+
+/// Encode the provided bytes into a hex string.
+pub fn to_hex<T: AsRef<[u8]>>(bytes: T) -> String {
+    let mut s = String::with_capacity(bytes.as_ref().len() * 2);
+    bytes.write_hex(&mut s).expect("Unable to write");
+    s
+}
+
+/// Encode the provided bytes into an upper-case hex string.
+pub fn to_hex_upper<T: AsRef<[u8]>>(bytes: T) -> String {
+    let mut s = String::with_capacity(bytes.as_ref().len() * 2);
+    bytes.write_hex_upper(&mut s).expect("Unable to write");
+    s
+}
+
+/// Encode each of the provided byte vectors into a hex string, returning one string per vector.
+pub fn to_hex_multiple(bytearray: &[Vec<u8>]) -> Vec<String> {
+    let mut result = Vec::new();
+    for bytes in bytearray {
+        result.push(to_hex(bytes))
+    }
+    result
+}
+
+/// Decode a hex string into bytes.
+pub fn from_hex(hex_str: &str) -> Result<Vec<u8>, HexError> {
+    let (hex_trim, _) = normalize_hex(hex_str)?;
+    let mut result = vec![0u8; hex_trim.len() / 2];
+    decode_to_slice(hex_str, &mut result)?;
     Ok(result)
 }
 
@@ -106,15 +300,82 @@ where
     ser.serialize_str(&t.to_hex())
 }
 
+/// Use a serde deserializer to deserialize a hex string into the given object.
+pub fn deserialize_from_hex<'de, D, T>(de: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Hex,
+{
+    let hex_str: String = serde::Deserialize::deserialize(de)?;
+    T::from_hex(&hex_str).map_err(SerdeDeError::custom)
+}
+
+/// A `serde::with` module for (de)serializing any [Hex] type to and from its hex string representation, e.g.
+/// `#[serde(with = "tari_utilities::hex::hex_serde")]`.
+pub mod hex_serde {
+    use serde::{Deserializer, Serializer};
+
+    use super::Hex;
+
+    /// Serialize `t` as a hex string.
+    pub fn serialize<S, T>(t: &T, ser: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Hex,
+    {
+        super::serialize_to_hex(t, ser)
+    }
+
+    /// Deserialize a hex string into `T`.
+    pub fn deserialize<'de, D, T>(de: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Hex,
+    {
+        super::deserialize_from_hex(de)
+    }
+}
+
+/// A `serde::with` module for (de)serializing a `Vec` of any [Hex] type to and from a `Vec` of hex strings, e.g.
+/// `#[serde(with = "tari_utilities::hex::hex_serde_vec")]`.
+pub mod hex_serde_vec {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Hex;
+
+    /// Serialize `items` as a vector of hex strings.
+    pub fn serialize<S, T>(items: &[T], ser: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Hex,
+    {
+        let hexed: Vec<String> = items.iter().map(Hex::to_hex).collect();
+        hexed.serialize(ser)
+    }
+
+    /// Deserialize a vector of hex strings into a `Vec<T>`.
+    pub fn deserialize<'de, D, T>(de: D) -> Result<Vec<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Hex,
+    {
+        let hexed = Vec::<String>::deserialize(de)?;
+        hexed
+            .into_iter()
+            .map(|s| T::from_hex(&s).map_err(serde::de::Error::custom))
+            .collect()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
     #[test]
     fn test_to_hex() {
-        assert_eq!(to_hex(&[0, 0, 0, 0]), "00000000");
-        assert_eq!(to_hex(&[10, 11, 12, 13]), "0a0b0c0d");
-        assert_eq!(to_hex(&[0, 0, 0, 255]), "000000ff");
+        assert_eq!(to_hex([0, 0, 0, 0]), "00000000");
+        assert_eq!(to_hex([10, 11, 12, 13]), "0a0b0c0d");
+        assert_eq!(to_hex([0, 0, 0, 255]), "000000ff");
     }
 
     #[test]
@@ -130,6 +391,146 @@ mod test {
         assert!(from_hex("🖖🥴").is_err());
     }
 
+    #[test]
+    fn test_to_hex_upper() {
+        assert_eq!(to_hex_upper([10, 11, 12, 13]), "0A0B0C0D");
+        assert_eq!(DummyKey(vec![10, 11, 12, 13]).to_hex_upper(), "0A0B0C0D");
+    }
+
+    #[test]
+    fn test_from_hex_case_insensitive() {
+        assert_eq!(from_hex("0A0B0C0D").unwrap(), from_hex("0a0b0c0d").unwrap());
+        assert_eq!(from_hex(&to_hex_upper([10, 11, 12, 13])).unwrap(), vec![10, 11, 12, 13]);
+    }
+
+    #[test]
+    fn test_from_hex_trait() {
+        assert_eq!(Vec::<u8>::decode("0a0b0c0d").unwrap(), vec![10, 11, 12, 13]);
+        assert!(Vec::<u8>::decode("8080gf").is_err());
+    }
+
+    #[test]
+    fn test_encode_to_slice() {
+        let mut out = [0u8; 8];
+        encode_to_slice([10u8, 11, 12, 13], &mut out).unwrap();
+        assert_eq!(&out, b"0a0b0c0d");
+        let mut bad = [0u8; 7];
+        assert!(matches!(
+            encode_to_slice([10u8, 11, 12, 13], &mut bad),
+            Err(HexError::InvalidBufferLength)
+        ));
+    }
+
+    #[test]
+    fn test_decode_to_slice() {
+        let mut out = [0u8; 4];
+        decode_to_slice("0a0b0c0d", &mut out).unwrap();
+        assert_eq!(out, [10, 11, 12, 13]);
+        let mut bad = [0u8; 3];
+        assert!(matches!(
+            decode_to_slice("0a0b0c0d", &mut bad),
+            Err(HexError::InvalidBufferLength)
+        ));
+    }
+
+    #[test]
+    fn test_decode_to_slice_invalid_character() {
+        let mut out = [0u8; 3];
+        let err = decode_to_slice("8080gf", &mut out).unwrap_err();
+        assert!(matches!(err, HexError::InvalidCharacter { c: 'g', index: 4 }));
+    }
+
+    #[test]
+    fn test_decode_to_slice_invalid_character_index_accounts_for_prefix_and_whitespace() {
+        // the index must be reported against the string the caller passed in, not the normalized one
+        let mut out = [0u8; 2];
+        let err = decode_to_slice("0x80gf", &mut out).unwrap_err();
+        assert!(matches!(err, HexError::InvalidCharacter { c: 'g', index: 4 }));
+
+        let err = from_hex(" 0x80gf").unwrap_err();
+        assert!(matches!(err, HexError::InvalidCharacter { c: 'g', index: 5 }));
+    }
+
+    #[test]
+    fn test_from_hex_ct() {
+        assert_eq!(from_hex_ct("0a0b0c0d").unwrap(), vec![10, 11, 12, 13]);
+        assert_eq!(from_hex_ct("0x800000ff").unwrap(), vec![128, 0, 0, 255]);
+        assert_eq!(from_hex_ct("0A0B0C0D").unwrap(), from_hex_ct("0a0b0c0d").unwrap());
+        assert!(from_hex_ct("800").is_err()); // Odd number of bytes
+        let err = from_hex_ct("8080gf").unwrap_err();
+        assert!(matches!(err, HexError::InvalidCharacter { c: 'g', index: 4 }));
+    }
+
+    #[test]
+    fn test_to_hex_ct() {
+        assert_eq!(to_hex_ct(&[10, 11, 12, 13]), "0a0b0c0d");
+        assert_eq!(to_hex_ct(&[10, 11, 12, 13]), to_hex([10, 11, 12, 13]));
+    }
+
+    #[test]
+    fn test_decode_to_slice_ct_writes_full_buffer_on_error() {
+        let mut out = [0xffu8; 3];
+        let err = decode_to_slice_ct("8080gf", &mut out).unwrap_err();
+        assert!(matches!(err, HexError::InvalidCharacter { c: 'g', index: 4 }));
+        // the buffer is fully written even though the input contained an invalid character
+        assert_eq!(out, [0x80, 0x80, 0x0f]);
+    }
+
+    #[test]
+    fn test_decode_to_slice_ct_invalid_character_index_accounts_for_prefix() {
+        // same requirement as decode_to_slice: the index is against the original (prefixed) string
+        let mut out = [0u8; 2];
+        let err = decode_to_slice_ct("0x80gf", &mut out).unwrap_err();
+        assert!(matches!(err, HexError::InvalidCharacter { c: 'g', index: 4 }));
+
+        let err = from_hex_ct("0x80gf").unwrap_err();
+        assert!(matches!(err, HexError::InvalidCharacter { c: 'g', index: 4 }));
+    }
+
+    #[test]
+    fn test_write_hex() {
+        let mut s = String::new();
+        [10u8, 11, 12, 13].write_hex(&mut s).unwrap();
+        assert_eq!(s, "0a0b0c0d");
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize, PartialEq, Debug)]
+    struct Wrapper(#[serde(with = "hex_serde")] DummyKey);
+
+    #[derive(PartialEq, Debug)]
+    struct DummyKey(Vec<u8>);
+
+    impl Hex for DummyKey {
+        fn from_hex(hex: &str) -> Result<Self, HexError> {
+            from_hex(hex).map(DummyKey)
+        }
+
+        fn to_hex(&self) -> String {
+            to_hex(&self.0)
+        }
+    }
+
+    #[test]
+    fn test_hex_serde() {
+        let wrapper = Wrapper(DummyKey(vec![10, 11, 12, 13]));
+        let json = serde_json::to_string(&wrapper).unwrap();
+        assert_eq!(json, "\"0a0b0c0d\"");
+        let round_tripped: Wrapper = serde_json::from_str(&json).unwrap();
+        assert_eq!(round_tripped, wrapper);
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize, PartialEq, Debug)]
+    struct VecWrapper(#[serde(with = "hex_serde_vec")] Vec<DummyKey>);
+
+    #[test]
+    fn test_hex_serde_vec() {
+        let wrapper = VecWrapper(vec![DummyKey(vec![10, 11]), DummyKey(vec![12, 13])]);
+        let json = serde_json::to_string(&wrapper).unwrap();
+        assert_eq!(json, "[\"0a0b\",\"0c0d\"]");
+        let round_tripped: VecWrapper = serde_json::from_str(&json).unwrap();
+        assert_eq!(round_tripped, wrapper);
+    }
+
     #[test]
     fn test_to_hex_multiple() {
         let ba = [vec![16u8, 32], vec![48, 64]];
@@ -152,7 +553,10 @@ mod test {
         let result = from_hex("1234567890ABCDEFG1");
         assert!(result.is_err());
         let err = result.unwrap_err();
-        assert!(matches!(err, HexError::InvalidCharacter(_)));
-        assert_eq!(err.to_string(), "Only hexadecimal characters (0-9,a-f) are permitted");
+        assert!(matches!(err, HexError::InvalidCharacter { c: 'G', index: 16 }));
+        assert_eq!(
+            err.to_string(),
+            "Invalid character 'G' at index 16: only hexadecimal characters (0-9,a-f,A-F) are permitted"
+        );
     }
 }